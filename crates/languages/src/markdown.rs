@@ -1,21 +1,200 @@
 use anyhow::{anyhow, bail, Context, Result};
 use async_trait::async_trait;
-use futures::StreamExt;
+use futures::{AsyncReadExt, AsyncWriteExt, StreamExt};
 use gpui::AsyncApp;
-use http_client::github::{latest_github_release, GitHubLspBinaryVersion};
+use http_client::github::latest_github_release;
 pub use language::*;
 use lsp::{LanguageServerBinary, LanguageServerName};
+use semver::Version;
+use serde_json::json;
+use sha2::{Digest, Sha256};
 use smol::fs::{self, File};
 use std::{any::Any, env::consts, ffi::OsString, path::PathBuf, sync::Arc};
-use util::{fs::remove_matching, maybe, ResultExt};
+use util::{fs::remove_matching, maybe, merge_json_value_into, ResultExt};
 
-pub struct MarkdownLspAdapter;
-
-fn server_binary_arguments() -> Vec<OsString> {
-    vec!["server".into()]
+/// Adapts a [`MarkdownServerBackend`] to Zed's `LspAdapter` interface.
+///
+/// The backend is what's actually specific to a given markdown language
+/// server (where to download it, how to name its release assets, what
+/// configuration namespace it expects); `MarkdownLspAdapter` just wires that
+/// into the generic download/cache/completion-label machinery shared by any
+/// backend.
+pub struct MarkdownLspAdapter {
+    backend: Arc<dyn MarkdownServerBackend>,
 }
 
 impl MarkdownLspAdapter {
+    pub fn new(backend: Arc<dyn MarkdownServerBackend>) -> Self {
+        Self { backend }
+    }
+
+    /// The backend's default configuration, with any user override found
+    /// under `lsp.<server name>.settings` merged on top. This is how users
+    /// disable noisy diagnostics or switch link styles per workspace.
+    fn merged_workspace_configuration(
+        &self,
+        delegate: &dyn LspAdapterDelegate,
+        cx: &AsyncApp,
+    ) -> Result<serde_json::Value> {
+        let mut configuration = self.backend.default_workspace_configuration();
+        let user_overrides = cx.update(|cx| {
+            language_server_settings(delegate, &self.backend.name(), cx)
+                .and_then(|settings| settings.settings.clone())
+        })?;
+        if let Some(user_overrides) = user_overrides {
+            merge_json_value_into(user_overrides, &mut configuration);
+        }
+        Ok(configuration)
+    }
+}
+
+impl Default for MarkdownLspAdapter {
+    fn default() -> Self {
+        Self::new(Arc::new(MarksmanBackend))
+    }
+}
+
+/// A markdown language server that `MarkdownLspAdapter` can manage.
+///
+/// Implement this to support a markdown server other than marksman (e.g. a
+/// pure-diagnostics linter, or a server only available via the system's
+/// package manager). Everything backend-specific — release coordinates,
+/// asset naming, launch arguments, and the `workspace/configuration`
+/// namespace — lives behind this trait instead of being hardcoded in the
+/// adapter.
+#[async_trait(?Send)]
+pub trait MarkdownServerBackend: 'static + Send + Sync {
+    fn name(&self) -> LanguageServerName;
+
+    fn server_binary_arguments(&self) -> Vec<OsString>;
+
+    async fn fetch_latest_server_version(
+        &self,
+        delegate: &dyn LspAdapterDelegate,
+    ) -> Result<Box<dyn 'static + Send + Any>>;
+
+    async fn fetch_server_binary(
+        &self,
+        version: Box<dyn 'static + Send + Any>,
+        container_dir: PathBuf,
+        delegate: &dyn LspAdapterDelegate,
+    ) -> Result<LanguageServerBinary>;
+
+    /// Locates an already-downloaded binary in `container_dir`, if any.
+    /// Backends only need to return a path here; `cached_server_binary`
+    /// attaches `server_binary_arguments()` itself so the launch arguments
+    /// can't drift from what `fetch_server_binary` uses.
+    async fn cached_server_binary_path(&self, container_dir: PathBuf) -> Option<PathBuf>;
+
+    async fn cached_server_binary(&self, container_dir: PathBuf) -> Option<LanguageServerBinary> {
+        Some(LanguageServerBinary {
+            path: self.cached_server_binary_path(container_dir).await?,
+            env: None,
+            arguments: self.server_binary_arguments(),
+        })
+    }
+
+    /// Server-specific `workspace/configuration` defaults. Sent both as
+    /// `initializationOptions` and in response to configuration requests,
+    /// with any user override merged on top by
+    /// `MarkdownLspAdapter::merged_workspace_configuration`.
+    fn default_workspace_configuration(&self) -> serde_json::Value {
+        json!({})
+    }
+}
+
+#[async_trait(?Send)]
+impl super::LspAdapter for MarkdownLspAdapter {
+    fn name(&self) -> LanguageServerName {
+        self.backend.name()
+    }
+
+    async fn check_if_user_installed(
+        &self,
+        delegate: &dyn LspAdapterDelegate,
+        _: Arc<dyn LanguageToolchainStore>,
+        _: &AsyncApp,
+    ) -> Option<LanguageServerBinary> {
+        let path = delegate.which(self.backend.name().as_ref()).await?;
+        Some(LanguageServerBinary {
+            path,
+            env: None,
+            arguments: self.backend.server_binary_arguments(),
+        })
+    }
+
+    async fn fetch_latest_server_version(
+        &self,
+        delegate: &dyn LspAdapterDelegate,
+    ) -> Result<Box<dyn 'static + Send + Any>> {
+        self.backend.fetch_latest_server_version(delegate).await
+    }
+
+    async fn fetch_server_binary(
+        &self,
+        version: Box<dyn 'static + Send + Any>,
+        container_dir: PathBuf,
+        delegate: &dyn LspAdapterDelegate,
+    ) -> Result<LanguageServerBinary> {
+        self.backend
+            .fetch_server_binary(version, container_dir, delegate)
+            .await
+    }
+
+    async fn cached_server_binary(
+        &self,
+        container_dir: PathBuf,
+        _: &dyn LspAdapterDelegate,
+    ) -> Option<LanguageServerBinary> {
+        self.backend.cached_server_binary(container_dir).await
+    }
+
+    async fn initialization_options(
+        self: Arc<Self>,
+        delegate: &dyn LspAdapterDelegate,
+        cx: &AsyncApp,
+    ) -> Result<Option<serde_json::Value>> {
+        Ok(Some(self.merged_workspace_configuration(delegate, cx)?))
+    }
+
+    async fn workspace_configuration(
+        &self,
+        delegate: &dyn LspAdapterDelegate,
+        _: Arc<dyn LanguageToolchainStore>,
+        cx: &AsyncApp,
+    ) -> Result<serde_json::Value> {
+        self.merged_workspace_configuration(delegate, cx)
+    }
+
+    async fn label_for_completion(
+        &self,
+        completion: &lsp::CompletionItem,
+        _language: &Arc<Language>,
+    ) -> Option<CodeLabel> {
+        match completion.kind {
+            Some(lsp::CompletionItemKind::REFERENCE) if completion.detail.is_some() => {
+                let detail = completion.detail.as_ref().unwrap();
+                let text = format!("{} - {}", detail, completion.label);
+                Some(CodeLabel::plain(text, None))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Release metadata for a marksman binary, including the digest published
+/// alongside it so downloads can be verified before they're ever executed.
+struct MarksmanVersion {
+    name: String,
+    url: String,
+    sha256: String,
+}
+
+/// Downloads [marksman](https://github.com/artempyanykh/marksman) releases
+/// from GitHub. This is the default backend.
+pub struct MarksmanBackend;
+
+impl MarksmanBackend {
     const SERVER_NAME: LanguageServerName = LanguageServerName::new_static("marksman");
 
     fn build_asset_name() -> Result<String> {
@@ -34,23 +213,13 @@ impl MarkdownLspAdapter {
 }
 
 #[async_trait(?Send)]
-impl super::LspAdapter for MarkdownLspAdapter {
+impl MarkdownServerBackend for MarksmanBackend {
     fn name(&self) -> LanguageServerName {
         Self::SERVER_NAME.clone()
     }
 
-    async fn check_if_user_installed(
-        &self,
-        delegate: &dyn LspAdapterDelegate,
-        _: Arc<dyn LanguageToolchainStore>,
-        _: &AsyncApp,
-    ) -> Option<LanguageServerBinary> {
-        let path = delegate.which(Self::SERVER_NAME.as_ref()).await?;
-        Some(LanguageServerBinary {
-            path,
-            env: None,
-            arguments: server_binary_arguments(),
-        })
+    fn server_binary_arguments(&self) -> Vec<OsString> {
+        vec!["server".into()]
     }
 
     async fn fetch_latest_server_version(
@@ -60,15 +229,40 @@ impl super::LspAdapter for MarkdownLspAdapter {
         let release =
             latest_github_release("artempyanykh/marksman", true, false, delegate.http_client())
                 .await?;
-        let asset_name = MarkdownLspAdapter::build_asset_name()?;
+        let asset_name = Self::build_asset_name()?;
         let asset = release
             .assets
             .iter()
             .find(|asset| asset.name == asset_name)
             .ok_or_else(|| anyhow!("no asset found matching {:?}", asset_name))?;
-        let version = GitHubLspBinaryVersion {
+
+        let checksum_name = format!("{asset_name}.sha256");
+        let checksum_asset = release
+            .assets
+            .iter()
+            .find(|asset| asset.name == checksum_name)
+            .ok_or_else(|| anyhow!("no checksum asset found matching {:?}", checksum_name))?;
+        let mut checksum_response = delegate
+            .http_client()
+            .get(&checksum_asset.browser_download_url, Default::default(), true)
+            .await
+            .context("error downloading checksum")?;
+        let mut checksum_body = String::new();
+        checksum_response
+            .body_mut()
+            .read_to_string(&mut checksum_body)
+            .await
+            .context("error reading checksum")?;
+        let sha256 = checksum_body
+            .split_whitespace()
+            .next()
+            .ok_or_else(|| anyhow!("checksum file {:?} was empty", checksum_name))?
+            .to_string();
+
+        let version = MarksmanVersion {
             name: release.tag_name,
             url: asset.browser_download_url.clone(),
+            sha256,
         };
         Ok(Box::new(version) as Box<_>)
     }
@@ -79,81 +273,239 @@ impl super::LspAdapter for MarkdownLspAdapter {
         container_dir: PathBuf,
         delegate: &dyn LspAdapterDelegate,
     ) -> Result<LanguageServerBinary> {
-        let version = version.downcast::<GitHubLspBinaryVersion>().unwrap();
+        let version = version.downcast::<MarksmanVersion>().unwrap();
         let binary_path = container_dir.join(format!("marksman-{}", version.name));
 
         if fs::metadata(&binary_path).await.is_err() {
+            let partial_path = container_dir.join(format!("marksman-{}.partial", version.name));
+
             let mut response = delegate
                 .http_client()
                 .get(&version.url, Default::default(), true)
                 .await
                 .context("error downloading release")?;
-            let mut file = File::create(&binary_path).await?;
             if !response.status().is_success() {
                 Err(anyhow!(
                     "download failed with status {}",
                     response.status().to_string()
                 ))?;
             }
-            futures::io::copy(response.body_mut(), &mut file).await?;
+
+            let mut file = File::create(&partial_path).await?;
+            let mut hasher = Sha256::new();
+            let mut buf = [0; 8192];
+            let mut body = response.body_mut();
+            loop {
+                let bytes_read = body.read(&mut buf).await?;
+                if bytes_read == 0 {
+                    break;
+                }
+                hasher.update(&buf[..bytes_read]);
+                file.write_all(&buf[..bytes_read]).await?;
+            }
+            file.flush().await?;
+            drop(file);
+
+            let digest = format!("{:x}", hasher.finalize());
+            if let Err(e) = verify_checksum(&version.name, &digest, &version.sha256) {
+                fs::remove_file(&partial_path).await.log_err();
+                return Err(e);
+            }
 
             #[cfg(not(windows))]
             {
                 fs::set_permissions(
-                    &binary_path,
+                    &partial_path,
                     <fs::Permissions as fs::unix::PermissionsExt>::from_mode(0o755),
                 )
                 .await?;
             }
 
+            fs::rename(&partial_path, &binary_path).await?;
+
             remove_matching(&container_dir, |entry| entry != binary_path).await;
         }
 
         Ok(LanguageServerBinary {
             path: binary_path,
             env: None,
-            arguments: server_binary_arguments(),
+            arguments: self.server_binary_arguments(),
         })
     }
 
-    async fn cached_server_binary(
+    async fn cached_server_binary_path(&self, container_dir: PathBuf) -> Option<PathBuf> {
+        get_cached_server_binary_path(container_dir).await
+    }
+
+    /// Marksman's `marksman`-namespaced configuration defaults. Covers the
+    /// diagnostics and completion/wiki-link knobs users most commonly want
+    /// to tune; unlisted marksman settings fall back to its own defaults.
+    /// Users override these per-workspace via `lsp.marksman.settings`.
+    fn default_workspace_configuration(&self) -> serde_json::Value {
+        json!({
+            "marksman": {
+                "diagnostics": {
+                    "dead_links": true,
+                    "broken_wiki_links": true,
+                    "duplicate_headings": false
+                },
+                "completion": {
+                    "candidates": "wiki_link"
+                },
+                "wiki_links": {
+                    "target_format": "file_stem"
+                }
+            }
+        })
+    }
+}
+
+/// A markdown server backend for servers Zed doesn't know how to download,
+/// e.g. a pure-diagnostics linter installed through the system's package
+/// manager. `check_if_user_installed` still finds it on `$PATH`; Zed just
+/// can't fetch or cache a copy on its own.
+pub struct ExternalMarkdownBackend {
+    pub name: LanguageServerName,
+    pub arguments: Vec<OsString>,
+}
+
+#[async_trait(?Send)]
+impl MarkdownServerBackend for ExternalMarkdownBackend {
+    fn name(&self) -> LanguageServerName {
+        self.name.clone()
+    }
+
+    fn server_binary_arguments(&self) -> Vec<OsString> {
+        self.arguments.clone()
+    }
+
+    async fn fetch_latest_server_version(
         &self,
-        container_dir: PathBuf,
         _: &dyn LspAdapterDelegate,
-    ) -> Option<LanguageServerBinary> {
-        get_cached_server_binary(container_dir).await
+    ) -> Result<Box<dyn 'static + Send + Any>> {
+        bail!(
+            "{} has no managed download; install it and ensure it's on PATH",
+            self.name.as_ref()
+        )
     }
 
-    async fn label_for_completion(
+    async fn fetch_server_binary(
         &self,
-        completion: &lsp::CompletionItem,
-        _language: &Arc<Language>,
-    ) -> Option<CodeLabel> {
-        match completion.kind {
-            Some(lsp::CompletionItemKind::REFERENCE) if completion.detail.is_some() => {
-                let detail = completion.detail.as_ref().unwrap();
-                let text = format!("{} - {}", detail, completion.label);
-                Some(CodeLabel::plain(text, None))
-            }
-            _ => None,
-        }
+        _: Box<dyn 'static + Send + Any>,
+        _: PathBuf,
+        _: &dyn LspAdapterDelegate,
+    ) -> Result<LanguageServerBinary> {
+        bail!(
+            "{} has no managed download; install it and ensure it's on PATH",
+            self.name.as_ref()
+        )
+    }
+
+    async fn cached_server_binary_path(&self, _: PathBuf) -> Option<PathBuf> {
+        None
     }
 }
 
-async fn get_cached_server_binary(container_dir: PathBuf) -> Option<LanguageServerBinary> {
+fn verify_checksum(version_name: &str, actual_digest: &str, expected_digest: &str) -> Result<()> {
+    if actual_digest != expected_digest {
+        bail!(
+            "checksum mismatch for marksman-{version_name}: expected {expected_digest}, got {actual_digest}"
+        );
+    }
+    Ok(())
+}
+
+/// Extracts the raw version string out of a cached `marksman-{version}`
+/// filename, rejecting `.partial` files left behind by interrupted
+/// downloads. The version isn't parsed here: marksman's tags have always
+/// been semver so far, but treating a future non-semver tag (date-based,
+/// build metadata, etc.) as "not a cached binary at all" would make every
+/// restart silently re-download, which is worse than falling back to a
+/// plain string compare.
+fn parse_cached_binary_version(path: &PathBuf) -> Option<String> {
+    let file_name = path.file_name()?.to_str()?;
+    let version = file_name.strip_prefix("marksman-")?;
+    if version.ends_with(".partial") {
+        return None;
+    }
+    Some(version.to_string())
+}
+
+/// Orders two marksman version strings, preferring semver comparison but
+/// falling back to a lexicographic compare when either fails to parse.
+fn compare_cached_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    match (
+        Version::parse(a.trim_start_matches('v')),
+        Version::parse(b.trim_start_matches('v')),
+    ) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+async fn get_cached_server_binary_path(container_dir: PathBuf) -> Option<PathBuf> {
     maybe!(async {
-        let mut last = None;
+        let mut newest: Option<(String, PathBuf)> = None;
         let mut entries = fs::read_dir(&container_dir).await?;
         while let Some(entry) = entries.next().await {
-            last = Some(entry?.path());
+            let path = entry?.path();
+            let Some(version) = parse_cached_binary_version(&path) else {
+                continue;
+            };
+            let is_newer = newest.as_ref().map_or(true, |(newest, _)| {
+                compare_cached_versions(&version, newest).is_gt()
+            });
+            if is_newer {
+                newest = Some((version, path));
+            }
         }
 
-        anyhow::Ok(LanguageServerBinary {
-            path: last.ok_or_else(|| anyhow!("no cached marksman binary"))?,
-            env: None,
-            arguments: Default::default(),
-        })
+        anyhow::Ok(
+            newest
+                .map(|(_, path)| path)
+                .ok_or_else(|| anyhow!("no cached marksman binary"))?,
+        )
     })
     .await
     .log_err()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_checksum_mismatch() {
+        let bytes = b"definitely a marksman binary";
+        let mut hasher = Sha256::new();
+        hasher.update(bytes);
+        let actual_digest = format!("{:x}", hasher.finalize());
+
+        assert!(verify_checksum("1.0.0", &actual_digest, &actual_digest).is_ok());
+        assert!(verify_checksum("1.0.0", &actual_digest, "deadbeef").is_err());
+    }
+
+    #[test]
+    fn extracts_version_and_ignores_partials() {
+        assert_eq!(
+            parse_cached_binary_version(&PathBuf::from("marksman-v1.2.3")),
+            Some("v1.2.3".to_string())
+        );
+        assert!(parse_cached_binary_version(&PathBuf::from("marksman-v1.2.3.partial")).is_none());
+        assert!(parse_cached_binary_version(&PathBuf::from("marksman.sha256")).is_none());
+    }
+
+    #[test]
+    fn compares_semver_versions_numerically() {
+        assert!(compare_cached_versions("v1.10.0", "v1.3.0").is_gt());
+        assert!(compare_cached_versions("v1.2.3", "v1.2.3").is_eq());
+    }
+
+    #[test]
+    fn falls_back_to_string_compare_for_non_semver_tags() {
+        // A non-semver tag shouldn't make the comparison panic or treat the
+        // file as incomparable; it should still sort, just lexicographically.
+        assert!(compare_cached_versions("2024-01-02", "2024-01-01").is_gt());
+        assert!(compare_cached_versions("v1.2.3", "nightly").is_gt());
+    }
+}